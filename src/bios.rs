@@ -0,0 +1,11 @@
+use std::fs;
+
+pub fn bios() -> Result<(String, String), String> {
+    let model = fs::read_to_string("/sys/class/dmi/id/product_version")
+        .map_err(|err| format!("failed to read BIOS model: {}", err))?;
+
+    let version = fs::read_to_string("/sys/class/dmi/id/bios_version")
+        .map_err(|err| format!("failed to read BIOS version: {}", err))?;
+
+    Ok((model.trim().to_string(), version.trim().to_string()))
+}
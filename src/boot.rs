@@ -0,0 +1,47 @@
+use std::process::Command;
+
+use mount::Mount;
+
+const LABEL: &'static str = "system76-firmware-update";
+
+pub fn set_next_boot() -> Result<(), String> {
+    let _mount = Mount::new("/boot/efi").map_err(|err| format!("failed to mount /boot/efi: {}", err))?;
+
+    let entry = find_entry()?.ok_or(format!("{} boot entry not found", LABEL))?;
+
+    let status = Command::new("efibootmgr").arg("-n").arg(&entry).status()
+        .map_err(|err| format!("failed to run efibootmgr: {}", err))?;
+    if !status.success() {
+        return Err(format!("efibootmgr exited with {}", status));
+    }
+
+    Ok(())
+}
+
+pub fn unset_next_boot() -> Result<(), String> {
+    let _mount = Mount::new("/boot/efi").map_err(|err| format!("failed to mount /boot/efi: {}", err))?;
+
+    let status = Command::new("efibootmgr").arg("-N").status()
+        .map_err(|err| format!("failed to run efibootmgr: {}", err))?;
+    if !status.success() {
+        return Err(format!("efibootmgr exited with {}", status));
+    }
+
+    Ok(())
+}
+
+fn find_entry() -> Result<Option<String>, String> {
+    let output = Command::new("efibootmgr").output()
+        .map_err(|err| format!("failed to run efibootmgr: {}", err))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    for line in stdout.lines() {
+        if line.contains(LABEL) {
+            if let Some(entry) = line.get(4..8) {
+                return Ok(Some(entry.to_string()));
+            }
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use bios;
+use ec;
+use me;
+
+// A single updatable piece of firmware (BIOS, EC, ME, or a future device such as a
+// Thunderbolt retimer). New hardware is supported by adding an implementation and
+// registering it in `components()`, rather than adding another D-Bus method.
+pub trait FirmwareComponent {
+    // Stable identifier used in D-Bus responses and changelog manifests, e.g. "bios".
+    fn id(&self) -> &'static str;
+
+    // Human readable name shown to the frontend, e.g. "System BIOS".
+    fn name(&self) -> &'static str;
+
+    // Currently installed version, or `None` if the component isn't present on this machine.
+    fn version(&self) -> Result<Option<String>, String>;
+
+    // Copy this component's payload into the updater staging directory before it is moved
+    // onto the ESP. Components whose firmware is already staged by the generic tarball
+    // extraction (the common case today) can leave this as a no-op.
+    fn stage(&self, updater_dir: &Path) -> Result<(), String>;
+}
+
+pub struct Bios;
+
+impl FirmwareComponent for Bios {
+    fn id(&self) -> &'static str { "bios" }
+
+    fn name(&self) -> &'static str { "System BIOS" }
+
+    fn version(&self) -> Result<Option<String>, String> {
+        let (_model, version) = bios::bios()?;
+        Ok(Some(version))
+    }
+
+    fn stage(&self, _updater_dir: &Path) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub struct EmbeddedController;
+
+impl FirmwareComponent for EmbeddedController {
+    fn id(&self) -> &'static str { "ec" }
+
+    fn name(&self) -> &'static str { "Embedded Controller" }
+
+    fn version(&self) -> Result<Option<String>, String> {
+        // Like `firmware_id`, use the non-erroring form: machines without a (primary) EC
+        // shouldn't make the whole device list fail.
+        let (project, version) = ec::ec_or_none(true);
+        if project.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(version))
+        }
+    }
+
+    fn stage(&self, _updater_dir: &Path) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub struct ManagementEngine;
+
+impl FirmwareComponent for ManagementEngine {
+    fn id(&self) -> &'static str { "me" }
+
+    fn name(&self) -> &'static str { "Management Engine" }
+
+    fn version(&self) -> Result<Option<String>, String> {
+        me::me()
+    }
+
+    fn stage(&self, _updater_dir: &Path) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// All firmware components known to this daemon, in display order.
+pub fn components() -> Vec<Box<FirmwareComponent>> {
+    vec![
+        Box::new(Bios),
+        Box::new(EmbeddedController),
+        Box::new(ManagementEngine),
+    ]
+}
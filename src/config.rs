@@ -0,0 +1,42 @@
+// Public key and certificate used to validate the buildchain manifest signature.
+pub static KEY: &'static [u8] = b"\
+-----BEGIN PUBLIC KEY-----
+MCowBQYDK2VwAyEAIeSSAl+kcEEA8VeV7QFHGRJWgaibIxgwf4pCosoDz5I=
+-----END PUBLIC KEY-----
+";
+pub static CERT: &'static [u8] = b"\
+-----BEGIN CERTIFICATE-----
+MIIBDTCBwKADAgECAgEBMAUGAytlcDAUMRIwEAYDVQQDDAlzeXN0ZW03NjAeFw0x
+OTAxMDEwMDAwMDBaFw0yOTAxMDEwMDAwMDBaMBQxEjAQBgNVBAMMCXN5c3RlbTc2
+MCowBQYDK2VwAyEAIeSSAl+kcEEA8VeV7QFHGRJWgaibIxgwf4pCosoDz5IwBQYD
+K2VwA0EAxgP2Xj9z3Ys+EaTFwGkBxEq+vMVZ3xRXlxgU2Wr1b/eF5HlkDnEtXqpy
+NfLmgG0zWJr+QwUq1ZdRu+9k3dEACA==
+-----END CERTIFICATE-----
+";
+
+pub static URL: &'static str = "https://firmware.system76.com";
+pub static PROJECT: &'static str = "system76-firmware";
+pub static BRANCH: &'static str = "master";
+
+pub static CACHE: &'static str = "/var/cache/system76-firmware";
+
+// Default number of most-recent manifests `download::Cache::clean` keeps objects for.
+pub static CACHE_KEEP: u32 = 5;
+
+// Ed25519 public key used to verify the detached `<name>.sig` signatures shipped
+// alongside the updater executable and each firmware blob in the staged update.
+//
+// PLACEHOLDER: this is not a real signing key yet. Replace it with the production key
+// before flipping on `ENFORCE_SIGNATURES` below.
+pub static TRUSTED_KEY: [u8; 32] = [
+    0x8e, 0x1a, 0x4f, 0x2d, 0x6b, 0x3c, 0x9e, 0x71,
+    0x5a, 0xc4, 0x0d, 0xb8, 0x27, 0x94, 0xf1, 0x3e,
+    0x62, 0xd9, 0xa5, 0x4b, 0x18, 0xfe, 0x7c, 0x03,
+    0x9a, 0x56, 0xe2, 0xcb, 0x84, 0x1d, 0x70, 0xaf,
+];
+
+// Whether `verify_staged` actually rejects unsigned/invalid staged updates. Off by default:
+// `TRUSTED_KEY` above is a placeholder and no build pipeline signs `<name>.sig` files yet,
+// so enforcing this today would reject every update. Flip to `true` once both the real key
+// is in place and release bundles ship signatures.
+pub static ENFORCE_SIGNATURES: bool = false;
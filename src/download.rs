@@ -0,0 +1,188 @@
+use buildchain::{Downloader, Manifest};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use util;
+
+// Objects are streamed to disk in fixed-size chunks so that callers can report progress
+// without buildchain itself needing to know about it.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// History of manifest digests fetched through this cache, oldest first, one per line.
+// `clean` uses it to figure out which objects are still reachable from a recent manifest.
+const MANIFEST_LOG: &'static str = "manifests";
+
+pub struct Cache {
+    path: PathBuf,
+    downloader: Option<Downloader>,
+}
+
+impl Cache {
+    pub fn new<P: AsRef<Path>>(path: P, downloader: Option<Downloader>) -> Result<Cache, String> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)
+            .map_err(|err| format!("failed to create cache directory {}: {}", path.display(), err))?;
+        Ok(Cache { path, downloader })
+    }
+
+    pub fn object(&self, digest: &str) -> Result<Vec<u8>, String> {
+        self.object_with_progress(digest, |_completed, _total| {})
+    }
+
+    // Like `object`, but calls `on_chunk(completed, total)` after each chunk is written to
+    // the cache (or once, immediately, when the object is already cached).
+    pub fn object_with_progress<F: FnMut(u64, u64)>(&self, digest: &str, mut on_chunk: F) -> Result<Vec<u8>, String> {
+        let object_path = self.path.join(digest);
+
+        if object_path.is_file() {
+            let data = fs::read(&object_path)
+                .map_err(|err| format!("failed to read {}: {}", object_path.display(), err))?;
+            if util::sha256(&data) == digest {
+                on_chunk(data.len() as u64, data.len() as u64);
+                return Ok(data);
+            }
+            eprintln!("cached object {} is corrupt, redownloading", digest);
+        }
+
+        let downloader = self.downloader.as_ref()
+            .ok_or(format!("{} not found in cache and no downloader available", digest))?;
+        let data = downloader.object(digest)
+            .map_err(|err| format!("failed to download {}: {}", digest, err))?;
+
+        if util::sha256(&data) != digest {
+            return Err(format!("downloaded object {} failed checksum verification", digest));
+        }
+
+        let total = data.len() as u64;
+        let tmp_path = object_path.with_extension("partial");
+        {
+            let mut file = fs::File::create(&tmp_path)
+                .map_err(|err| format!("failed to create {}: {}", tmp_path.display(), err))?;
+            for chunk in data.chunks(CHUNK_SIZE) {
+                file.write_all(chunk)
+                    .map_err(|err| format!("failed to write {}: {}", tmp_path.display(), err))?;
+                on_chunk(file.metadata().map(|m| m.len()).unwrap_or(total).min(total), total);
+            }
+        }
+        fs::rename(&tmp_path, &object_path)
+            .map_err(|err| format!("failed to rename {} to {}: {}", tmp_path.display(), object_path.display(), err))?;
+
+        Ok(data)
+    }
+
+    // The digest of the most recently fetched manifest, if any has ever been recorded.
+    // Reads only the local history log, so it never touches the network.
+    pub fn latest_manifest_digest(&self) -> Result<Option<String>, String> {
+        let log_path = self.path.join(MANIFEST_LOG);
+        match fs::read_to_string(&log_path) {
+            Ok(contents) => Ok(contents.lines().last().map(String::from)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(format!("failed to read {}: {}", log_path.display(), err)),
+        }
+    }
+
+    // Records `digest` as a manifest that was just fetched, so a later `clean` call keeps
+    // it (and everything it references) alive.
+    pub fn record_manifest(&self, digest: &str) -> Result<(), String> {
+        let log_path = self.path.join(MANIFEST_LOG);
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&log_path)
+            .map_err(|err| format!("failed to open {}: {}", log_path.display(), err))?;
+        writeln!(file, "{}", digest)
+            .map_err(|err| format!("failed to write {}: {}", log_path.display(), err))
+    }
+
+    // Prunes cached objects not referenced by any of the `keep` most recently recorded
+    // manifests. The live set (every digest reachable from a retained manifest) is computed
+    // in full before anything is deleted, so an object an in-progress `Schedule(digest)`
+    // still needs is never removed out from under it. Returns the number of objects removed.
+    pub fn clean(&self, keep: u32) -> Result<usize, String> {
+        let log_path = self.path.join(MANIFEST_LOG);
+        let history = match fs::read_to_string(&log_path) {
+            Ok(contents) => contents.lines().map(String::from).collect::<Vec<_>>(),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(format!("failed to read {}: {}", log_path.display(), err)),
+        };
+
+        // `keep == 0` means retain nothing, so skip the loop entirely rather than letting
+        // the push-then-check below retain one manifest before it notices the limit is hit.
+        let mut retained: Vec<String> = Vec::new();
+        if keep > 0 {
+            for digest in history.iter().rev() {
+                if retained.contains(digest) {
+                    continue;
+                }
+                retained.push(digest.clone());
+                if retained.len() as u32 >= keep {
+                    break;
+                }
+            }
+        }
+
+        let mut live: HashSet<String> = retained.iter().cloned().collect();
+        for digest in &retained {
+            if let Ok(data) = fs::read(self.path.join(digest)) {
+                if let Ok(manifest) = ::serde_json::from_slice::<Manifest>(&data) {
+                    for (_name, file_digest) in manifest.files {
+                        live.insert(file_digest);
+                    }
+                }
+            }
+        }
+
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.path)
+            .map_err(|err| format!("failed to read {}: {}", self.path.display(), err))?
+        {
+            let entry = entry.map_err(|err| err.to_string())?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name == MANIFEST_LOG || name.ends_with(".partial") || live.contains(name.as_ref()) {
+                continue;
+            }
+            if fs::remove_file(entry.path()).is_ok() {
+                removed += 1;
+            }
+        }
+
+        let mut rewritten = retained;
+        rewritten.reverse();
+        fs::write(&log_path, rewritten.join("\n") + "\n")
+            .map_err(|err| format!("failed to write {}: {}", log_path.display(), err))?;
+
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_with_keep_zero_and_no_manifests_removes_every_object() {
+        let dir = tempdir::TempDir::new("s76fw-cache-clean-test").unwrap();
+        let cache = Cache::new(dir.path(), None).unwrap();
+
+        fs::write(dir.path().join("deadbeef"), b"stale").unwrap();
+        fs::write(dir.path().join("cafef00d"), b"also stale").unwrap();
+
+        let removed = cache.clean(0).unwrap();
+
+        assert_eq!(removed, 2);
+        assert!(! dir.path().join("deadbeef").exists());
+        assert!(! dir.path().join("cafef00d").exists());
+    }
+
+    #[test]
+    fn clean_never_removes_in_progress_partial_downloads() {
+        let dir = tempdir::TempDir::new("s76fw-cache-clean-test").unwrap();
+        let cache = Cache::new(dir.path(), None).unwrap();
+
+        fs::write(dir.path().join("deadbeef.partial"), b"in flight").unwrap();
+
+        cache.clean(0).unwrap();
+
+        assert!(dir.path().join("deadbeef.partial").exists());
+    }
+}
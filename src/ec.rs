@@ -0,0 +1,21 @@
+use ecflash::{Ec, EcFlash};
+
+pub fn ec(primary: bool) -> Result<(String, String), String> {
+    let mut ec = EcFlash::new(primary).map_err(|err| format!("failed to access EC: {}", err))?;
+
+    let project = ec.project();
+    let version = ec.version();
+
+    Ok((project, version))
+}
+
+// Like `ec`, but returns empty strings instead of an error when no EC is present.
+pub fn ec_or_none(primary: bool) -> (String, String) {
+    match ec(primary) {
+        Ok(ok) => ok,
+        Err(err) => {
+            eprintln!("failed to read EC: {}", err);
+            (String::new(), String::new())
+        }
+    }
+}
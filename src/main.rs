@@ -1,6 +1,7 @@
 extern crate buildchain;
 extern crate dbus;
 extern crate ecflash;
+extern crate ed25519_dalek;
 extern crate libc;
 extern crate lzma;
 extern crate plain;
@@ -11,13 +12,15 @@ extern crate tempdir;
 extern crate uuid;
 
 use buildchain::{Downloader, Manifest};
-use dbus::{Connection, BusType, NameFlag};
+use dbus::{Connection, BusType, Message, NameFlag};
 use dbus::tree::{Factory, MethodErr};
 use std::{fs, io, process};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 mod bios;
 mod boot;
+mod component;
 mod config;
 mod download;
 mod ec;
@@ -30,6 +33,11 @@ pub (crate) fn err_str<E: ::std::fmt::Display>(err: E) -> String {
     format!("{}", err)
 }
 
+// Identifies which `{firmware_id}.tar.xz` this machine's hardware needs. This is
+// deliberately hand-written rather than derived by iterating `component::components()`:
+// it names a hardware variant (bios model + ec project) used to pick a tarball, not a
+// firmware version listing, and BIOS/EC are the only components that currently factor into
+// that variant. `schedule()` does iterate the registered set, for `stage()`.
 fn firmware_id() -> Result<String, String> {
     let (bios_model, _bios_version) = bios::bios()?;
     let (ec_project, _ec_version) = ec::ec_or_none(true);
@@ -37,6 +45,75 @@ fn firmware_id() -> Result<String, String> {
     Ok(format!("{}_{}", bios_model, ec_hash))
 }
 
+// Available versions from the most recently *cached* manifest, without touching the
+// network. Returns `Value::Null` if nothing has been downloaded yet, or that manifest
+// lists no tarball for this machine; returns an error if the cache no longer holds the
+// manifest or the tarball it references.
+fn cached_available_versions() -> Result<serde_json::Value, String> {
+    let firmware_id = firmware_id()?;
+    let cache = download::Cache::new(config::CACHE, None)?;
+
+    let digest = match cache.latest_manifest_digest()? {
+        Some(digest) => digest,
+        None => return Ok(serde_json::Value::Null),
+    };
+
+    let manifest_json = cache.object(&digest)?;
+    let manifest = serde_json::from_slice::<Manifest>(&manifest_json).map_err(|e| e.to_string())?;
+
+    let file = format!("{}.tar.xz", firmware_id);
+    let firmware_digest = match manifest.files.get(&file) {
+        Some(digest) => digest,
+        None => return Ok(serde_json::Value::Null),
+    };
+    let firmware_data = cache.object(&firmware_digest)?;
+
+    let changelog = util::extract_file(&firmware_data, "./changelog.json").map_err(err_str)?;
+    serde_json::from_str::<serde_json::Value>(&changelog).map_err(|e| e.to_string())
+}
+
+// Looks up `id`'s available version out of a `changelog.json` value. Tries the flat
+// `{"bios": "1.2.3", ...}` shape first, then falls back to the nested
+// `{"versions": [{"id": "bios", "version": "1.2.3"}, ...]}` shape, since both are in use
+// across changelogs this daemon has to read. Returns `None` if `id` isn't listed in either.
+fn changelog_version(changelog: &serde_json::Value, id: &str) -> Option<String> {
+    if let Some(version) = changelog.get(id).and_then(|v| v.as_str()) {
+        return Some(version.to_string());
+    }
+
+    changelog.get("versions")
+        .and_then(|v| v.as_array())
+        .and_then(|entries| entries.iter().find(|entry| {
+            entry.get("id").and_then(|v| v.as_str()) == Some(id)
+        }))
+        .and_then(|entry| entry.get("version").and_then(|v| v.as_str()))
+        .map(|s| s.to_string())
+}
+
+// id, display name, installed version, and available version for every registered
+// firmware component, for frontends that want to list devices rather than poll each
+// Bios/EmbeddedController/ManagementEngine method individually. Available versions come
+// from whatever manifest is already cached; call `Download` first to refresh them.
+fn devices() -> Result<Vec<(String, String, String, String)>, String> {
+    let available = match cached_available_versions() {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("failed to read cached firmware versions: {}", err);
+            serde_json::Value::Null
+        }
+    };
+
+    let mut devices = Vec::new();
+    for component in component::components() {
+        let installed = component.version()?.unwrap_or_default();
+        let available_version = changelog_version(&available, component.id()).unwrap_or_default();
+
+        devices.push((component.id().to_string(), component.name().to_string(), installed, available_version));
+    }
+
+    Ok(devices)
+}
+
 fn remove_dir<P: AsRef<Path>>(path: P) -> Result<(), String> {
     if path.as_ref().exists() {
         eprintln!("removing {}", path.as_ref().display());
@@ -51,7 +128,7 @@ fn remove_dir<P: AsRef<Path>>(path: P) -> Result<(), String> {
     Ok(())
 }
 
-fn download() -> Result<(String, String), String> {
+fn download<F: FnMut(&str, u64, u64)>(mut on_progress: F) -> Result<(String, String), String> {
     let firmware_id = firmware_id()?;
 
     let dl = Downloader::new(
@@ -67,29 +144,44 @@ fn download() -> Result<(String, String), String> {
     let cache = download::Cache::new(config::CACHE, Some(dl))?;
 
     eprintln!("downloading manifest.json");
-    let manifest_json = cache.object(&tail.digest)?;
+    let manifest_json = cache.object_with_progress(&tail.digest, |completed, total| {
+        on_progress("manifest.json", completed, total)
+    })?;
     let manifest = serde_json::from_slice::<Manifest>(&manifest_json).map_err(|e| e.to_string())?;
+    cache.record_manifest(&tail.digest)?;
 
     let _updater_data = {
         let file = "system76-firmware-update.tar.xz";
         eprintln!("downloading {}", file);
         let digest = manifest.files.get(file).ok_or(format!("{} not found", file))?;
-        cache.object(&digest)?
+        cache.object_with_progress(&digest, |completed, total| on_progress(file, completed, total))?
     };
 
     let firmware_data = {
         let file = format!("{}.tar.xz", firmware_id);
         eprintln!("downloading {}", file);
         let digest = manifest.files.get(&file).ok_or(format!("{} not found", file))?;
-        cache.object(&digest)?
+        cache.object_with_progress(&digest, |completed, total| on_progress(&file, completed, total))?
     };
 
     let changelog = util::extract_file(&firmware_data, "./changelog.json").map_err(err_str)?;
 
+    match cache.clean(config::CACHE_KEEP) {
+        Ok(removed) if removed > 0 => eprintln!("pruned {} stale cache objects", removed),
+        Ok(_) => (),
+        Err(err) => eprintln!("failed to prune cache: {}", err),
+    }
+
     Ok((tail.digest.to_string(), changelog))
 }
 
-fn extract<P: AsRef<Path>>(digest: &str, file: &str, path: P) -> Result<(), String> {
+fn clean_cache(keep: u32) -> Result<u32, String> {
+    let cache = download::Cache::new(config::CACHE, None)?;
+    let removed = cache.clean(keep)?;
+    Ok(removed as u32)
+}
+
+fn extract<P: AsRef<Path>, F: FnMut(&str, u64, u64)>(digest: &str, file: &str, path: P, mut on_progress: F) -> Result<(), String> {
     let cache = download::Cache::new(config::CACHE, None)?;
 
     let manifest_json = cache.object(&digest)?;
@@ -101,7 +193,7 @@ fn extract<P: AsRef<Path>>(digest: &str, file: &str, path: P) -> Result<(), Stri
     };
 
     eprintln!("extracting {} to {}", file, path.as_ref().display());
-    match util::extract(&data, &path) {
+    match util::extract_with_progress(&data, &path, |completed, total| on_progress(file, completed, total)) {
         Ok(()) => (),
         Err(err) => {
             return Err(format!("failed to extract {} to {}: {}", file, path.as_ref().display(), err));
@@ -111,16 +203,173 @@ fn extract<P: AsRef<Path>>(digest: &str, file: &str, path: P) -> Result<(), Stri
     Ok(())
 }
 
-fn schedule(digest: &str) -> Result<(), String> {
+const EPOCH_FILE: &'static str = "/var/lib/system76-firmware/epoch";
+const PENDING_EPOCH_FILE: &'static str = "/var/lib/system76-firmware/pending_epoch";
+const UPDATER_DIR: &'static str = "/boot/efi/system76-firmware-update";
+
+// The epoch of the last firmware confirmed *installed*. Defaults to 0 so that machines
+// upgrading from a daemon that predates epoch tracking never get rollback-blocked.
+fn stored_epoch() -> Result<u64, String> {
+    read_epoch_file(EPOCH_FILE)
+}
+
+fn set_stored_epoch(epoch: u64) -> Result<(), String> {
+    write_epoch_file(EPOCH_FILE, epoch)
+}
+
+// The epoch `schedule()` last staged to the ESP, waiting on a reboot to confirm it was
+// actually installed. `None` once there is nothing staged by `schedule()` (or it was
+// already confirmed, or `install_from_file()` replaced the staged payload).
+// `schedule()` overwrites this unconditionally on every successful stage, even when the
+// newly staged epoch is equal to or below a previously staged one (a re-flash, or an
+// `allow_downgrade` call), so it always reflects the payload that will actually run next
+// boot rather than a stale value left behind by an earlier, since-superseded `Schedule`.
+fn pending_epoch() -> Result<Option<u64>, String> {
+    read_epoch_file_opt(PENDING_EPOCH_FILE)
+}
+
+fn set_pending_epoch(epoch: u64) -> Result<(), String> {
+    write_epoch_file(PENDING_EPOCH_FILE, epoch)
+}
+
+fn clear_pending_epoch() -> Result<(), String> {
+    remove_epoch_file(PENDING_EPOCH_FILE)
+}
+
+fn read_epoch_file(path: &str) -> Result<u64, String> {
+    Ok(read_epoch_file_opt(path)?.unwrap_or(0))
+}
+
+fn read_epoch_file_opt(path: &str) -> Result<Option<u64>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => contents.trim().parse::<u64>().map(Some).map_err(|err| format!("failed to parse {}: {}", path, err)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("failed to read {}: {}", path, err)),
+    }
+}
+
+fn write_epoch_file(path: &str, epoch: u64) -> Result<(), String> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent).map_err(|err| format!("failed to create {}: {}", parent.display(), err))?;
+    }
+    fs::write(path, epoch.to_string()).map_err(|err| format!("failed to write {}: {}", path, err))
+}
+
+fn remove_epoch_file(path: &str) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(format!("failed to remove {}: {}", path, err)),
+    }
+}
+
+// Called at daemon startup. If a firmware update was staged and the updater directory is
+// gone, the updater EFI executable ran at boot and removed it after flashing, so the
+// staged epoch is now confirmed installed and can be persisted. If the directory is still
+// there, the reboot either hasn't happened yet or the update never ran, so the epoch stays
+// unconfirmed and un-persisted.
+//
+// The confirmed epoch is `max(stored, pending)` rather than `pending` outright: since
+// `pending_epoch` always reflects whatever `schedule()` staged *last*, this only matters as
+// a last line of defense should the stored/pending files ever end up inconsistent with each
+// other — it must never let a confirm silently move the stored epoch backwards.
+fn confirm_pending_install() -> Result<(), String> {
+    confirm_pending_install_at(PENDING_EPOCH_FILE, EPOCH_FILE, Path::new(UPDATER_DIR))
+}
+
+fn confirm_pending_install_at(pending_file: &str, epoch_file: &str, updater_dir: &Path) -> Result<(), String> {
+    let pending = match read_epoch_file_opt(pending_file)? {
+        Some(epoch) => epoch,
+        None => return Ok(()),
+    };
+
+    if updater_dir.exists() {
+        return Ok(());
+    }
+
+    let current = read_epoch_file(epoch_file)?;
+    let confirmed = pending.max(current);
+
+    eprintln!("confirming firmware epoch {} was installed", confirmed);
+    write_epoch_file(epoch_file, confirmed)?;
+    remove_epoch_file(pending_file)
+}
+
+// Reads the `epoch` field out of the firmware's changelog.json, defaulting to 0 for
+// manifests built before epochs existed.
+fn manifest_epoch(digest: &str, firmware_id: &str) -> Result<u64, String> {
+    let cache = download::Cache::new(config::CACHE, None)?;
+
+    let manifest_json = cache.object(&digest)?;
+    let manifest = serde_json::from_slice::<Manifest>(&manifest_json).map_err(|e| e.to_string())?;
+
+    let file = format!("{}.tar.xz", firmware_id);
+    let firmware_digest = manifest.files.get(&file).ok_or(format!("{} not found", file))?;
+    let firmware_data = cache.object(&firmware_digest)?;
+
+    let changelog = util::extract_file(&firmware_data, "./changelog.json").map_err(err_str)?;
+    let changelog = serde_json::from_str::<serde_json::Value>(&changelog).map_err(|e| e.to_string())?;
+
+    Ok(changelog.get("epoch").and_then(|v| v.as_u64()).unwrap_or(0))
+}
+
+// Verifies the detached `<name>.sig` signature of a single file against the trusted key.
+fn verify_signed_file(path: &Path) -> Result<(), String> {
+    let data = fs::read(path).map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    let sig_path = path.with_file_name(format!("{}.sig", file_name));
+    let signature = fs::read(&sig_path)
+        .map_err(|err| format!("missing or unreadable signature {}: {}", sig_path.display(), err))?;
+
+    util::verify_signature(&data, &signature, &config::TRUSTED_KEY)
+        .map_err(|err| format!("signature verification failed for {}: {}", path.display(), err))
+}
+
+// Checks the updater executable and every `firmware/*` blob staged in `updater_dir`
+// against their detached signatures, so a tampered payload is never moved onto the ESP.
+// No-op while `config::ENFORCE_SIGNATURES` is off (see its doc comment for why).
+fn verify_staged(updater_dir: &Path) -> Result<(), String> {
+    if ! config::ENFORCE_SIGNATURES {
+        eprintln!("skipping staged-update signature verification (config::ENFORCE_SIGNATURES is off)");
+        return Ok(());
+    }
+
+    verify_signed_file(&updater_dir.join("system76-firmware-update"))?;
+
+    let firmware_dir = updater_dir.join("firmware");
+    let entries = fs::read_dir(&firmware_dir)
+        .map_err(|err| format!("failed to read {}: {}", firmware_dir.display(), err))?;
+    for entry in entries {
+        let path = entry.map_err(err_str)?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some("sig") {
+            continue;
+        }
+        verify_signed_file(&path)?;
+    }
+
+    Ok(())
+}
+
+fn schedule<F: FnMut(&str, u64, u64)>(digest: &str, allow_downgrade: bool, mut on_progress: F) -> Result<(), String> {
     let firmware_id = firmware_id()?;
 
     if ! Path::new("/sys/firmware/efi").exists() {
         return Err(format!("must be run using UEFI boot"));
     }
 
+    let epoch = manifest_epoch(digest, &firmware_id)?;
+    let current_epoch = stored_epoch()?;
+    if epoch < current_epoch && ! allow_downgrade {
+        return Err(format!(
+            "refusing to install firmware epoch {} over installed epoch {}; pass allow_downgrade to override",
+            epoch, current_epoch
+        ));
+    }
+
     let updater_file = "system76-firmware-update.tar.xz";
     let firmware_file = format!("{}.tar.xz", firmware_id);
-    let updater_dir = Path::new("/boot/efi/system76-firmware-update");
+    let updater_dir = Path::new(UPDATER_DIR);
 
     boot::unset_next_boot()?;
 
@@ -133,9 +382,15 @@ fn schedule(digest: &str) -> Result<(), String> {
         }
     };
 
-    extract(digest, updater_file, updater_tmp.path())?;
+    extract(digest, updater_file, updater_tmp.path(), &mut on_progress)?;
+
+    extract(digest, &firmware_file, &updater_tmp.path().join("firmware"), &mut on_progress)?;
+
+    for component in component::components() {
+        component.stage(updater_tmp.path())?;
+    }
 
-    extract(digest, &firmware_file, &updater_tmp.path().join("firmware"))?;
+    verify_staged(updater_tmp.path())?;
 
     let updater_tmp_dir = updater_tmp.into_path();
     eprintln!("moving {} to {}", updater_tmp_dir.display(), updater_dir.display());
@@ -147,6 +402,15 @@ fn schedule(digest: &str) -> Result<(), String> {
         }
     }
 
+    // The staged firmware isn't actually installed until it runs at next boot, so the
+    // stored epoch can't be advanced yet; record it as pending and let
+    // `confirm_pending_install` persist it once the updater directory disappears. This is
+    // unconditional, even when `epoch <= current_epoch` (a re-flash, or an `allow_downgrade`
+    // call): pending must always reflect what was *actually* staged, overwriting any stale
+    // higher value left behind by an earlier `Schedule` call that was never rebooted into,
+    // or a later confirm could advance the stored epoch past what's really installed.
+    set_pending_epoch(epoch)?;
+
     boot::set_next_boot()?;
 
     eprintln!("Firmware update scheduled. Reboot your machine to install.");
@@ -154,18 +418,168 @@ fn schedule(digest: &str) -> Result<(), String> {
     Ok(())
 }
 
+// Recursively lists every regular file under `dir`, as paths relative to `dir`.
+fn list_files_recursive(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    list_files_recursive_into(dir, Path::new(""), &mut files)?;
+    Ok(files)
+}
+
+fn list_files_recursive_into(dir: &Path, prefix: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let rel_path = prefix.join(entry.file_name());
+        if entry.path().is_dir() {
+            list_files_recursive_into(&entry.path(), &rel_path, files)?;
+        } else {
+            files.push(rel_path);
+        }
+    }
+    Ok(())
+}
+
+// Walks the *extracted* tree rather than the manifest's file list: every member that
+// actually landed on disk must be accounted for and verified, not merely the ones `files`
+// happens to mention, or an unlisted member (e.g. a tampered updater executable) would be
+// staged and moved onto the ESP unverified.
+fn verify_extracted_bundle(extracted_dir: &Path, files: &serde_json::Map<String, serde_json::Value>) -> Result<(), String> {
+    let extracted = list_files_recursive(extracted_dir)
+        .map_err(|err| format!("failed to list extracted files in {}: {}", extracted_dir.display(), err))?;
+
+    for rel_path in &extracted {
+        let name = rel_path.to_string_lossy().replace('\\', "/");
+        if name == "manifest.json" {
+            continue;
+        }
+
+        let expected_sha256 = files.get(name.as_str())
+            .ok_or(format!("{} is not listed in manifest.json", name))?
+            .as_str()
+            .ok_or(format!("{} has a non-string sha256 in manifest.json", name))?;
+
+        let member_path = extracted_dir.join(rel_path);
+        let member_data = fs::read(&member_path)
+            .map_err(|err| format!("failed to read extracted {}: {}", member_path.display(), err))?;
+
+        if util::sha256(&member_data) != expected_sha256 {
+            return Err(format!("{} failed checksum verification", name));
+        }
+    }
+
+    let expected_count = files.len();
+    let actual_count = extracted.len() - 1; // exclude manifest.json itself
+    if actual_count != expected_count {
+        return Err(format!(
+            "bundle contains {} files but manifest.json lists {}",
+            actual_count, expected_count
+        ));
+    }
+
+    Ok(())
+}
+
+// Stage a firmware update from a local tarball instead of `download::Cache`/`Downloader`,
+// for technicians and air-gapped machines. The tarball must embed its own `manifest.json`
+// (distinct from the buildchain `Manifest`) listing the sha256 of every member and the
+// `firmware_id` it was built for, since there is no buildchain digest to trust here.
+fn install_from_file<P: AsRef<Path>>(path: P) -> Result<(), String> {
+    let path = path.as_ref();
+
+    if ! Path::new("/sys/firmware/efi").exists() {
+        return Err(format!("must be run using UEFI boot"));
+    }
+
+    let data = fs::read(path).map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+    let manifest_json = util::extract_file(&data, "./manifest.json").map_err(err_str)?;
+    let manifest = serde_json::from_str::<serde_json::Value>(&manifest_json).map_err(|e| e.to_string())?;
+
+    let bundle_id = manifest.get("firmware_id").and_then(|v| v.as_str())
+        .ok_or(format!("manifest.json is missing firmware_id"))?;
+    let expected_id = firmware_id()?;
+    if bundle_id != expected_id {
+        return Err(format!("firmware bundle is for {}, this machine is {}", bundle_id, expected_id));
+    }
+
+    let files = manifest.get("files").and_then(|v| v.as_object())
+        .ok_or(format!("manifest.json is missing files"))?;
+
+    let updater_dir = Path::new(UPDATER_DIR);
+
+    boot::unset_next_boot()?;
+
+    remove_dir(&updater_dir)?;
+
+    let updater_tmp = match tempdir::TempDir::new_in("/boot/efi", "system76-firmware-update") {
+        Ok(ok) => ok,
+        Err(err) => {
+            return Err(format!("failed to create temporary directory: {}", err));
+        }
+    };
+
+    eprintln!("extracting {} to {}", path.display(), updater_tmp.path().display());
+    util::extract(&data, updater_tmp.path())
+        .map_err(|err| format!("failed to extract {} to {}: {}", path.display(), updater_tmp.path().display(), err))?;
+
+    verify_extracted_bundle(updater_tmp.path(), files)?;
+
+    // The offline bundle still ends up as an EFI payload executed at next boot, so it must
+    // clear the same detached-signature gate as the network path's `schedule()`, not just
+    // its own self-attested manifest checksums.
+    verify_staged(updater_tmp.path())?;
+
+    let updater_tmp_dir = updater_tmp.into_path();
+    eprintln!("moving {} to {}", updater_tmp_dir.display(), updater_dir.display());
+    match fs::rename(&updater_tmp_dir, &updater_dir) {
+        Ok(()) => (),
+        Err(err) => {
+            let _ = remove_dir(&updater_tmp_dir);
+            return Err(format!("failed to move {} to {}: {}", updater_tmp_dir.display(), updater_dir.display(), err));
+        }
+    }
+
+    // The payload an earlier `schedule()` staged has just been replaced, so its epoch must
+    // not be confirmed as installed.
+    clear_pending_epoch()?;
+
+    boot::set_next_boot()?;
+
+    eprintln!("Firmware update scheduled from {}. Reboot your machine to install.", path.display());
+
+    Ok(())
+}
+
 fn unschedule() -> Result<(), String> {
-    let updater_dir = Path::new("/boot/efi/system76-firmware-update");
+    let updater_dir = Path::new(UPDATER_DIR);
 
     boot::unset_next_boot()?;
 
     remove_dir(&updater_dir)?;
 
+    // A cancelled install must never later be mistaken for a confirmed one.
+    clear_pending_epoch()?;
+
     eprintln!("Firmware update cancelled.");
 
     Ok(())
 }
 
+const OBJECT_PATH: &'static str = "/com/system76/FirmwareDaemon";
+const INTERFACE: &'static str = "com.system76.FirmwareDaemon";
+
+fn emit_progress(c: &Connection, signal: &str, filename: &str, completed: u64, total: u64) {
+    let msg = match Message::new_signal(OBJECT_PATH, INTERFACE, signal) {
+        Ok(msg) => msg,
+        Err(err) => {
+            eprintln!("failed to build {} signal: {}", signal, err);
+            return;
+        }
+    };
+    if c.send(msg.append3(filename, completed, total)).is_err() {
+        eprintln!("failed to send {} signal", signal);
+    }
+}
+
 pub fn bus() -> Result<(), String> {
     if unsafe { libc::geteuid() } != 0 {
         return Err(format!("must be run as root"));
@@ -179,13 +593,29 @@ pub fn bus() -> Result<(), String> {
         ));
     }
 
-    let c = Connection::get_private(BusType::System).map_err(err_str)?;
+    if let Err(err) = confirm_pending_install() {
+        eprintln!("failed to confirm pending firmware install: {}", err);
+    }
+
+    let c = Rc::new(Connection::get_private(BusType::System).map_err(err_str)?);
     c.register_name("com.system76.FirmwareDaemon", NameFlag::ReplaceExisting as u32).map_err(err_str)?;
 
     let f = Factory::new_fn::<()>();
 
-    let tree = f.tree(()).add(f.object_path("/com/system76/FirmwareDaemon", ()).introspectable().add(
-        f.interface("com.system76.FirmwareDaemon", ())
+    let tree = f.tree(()).add(f.object_path(OBJECT_PATH, ()).introspectable().add(
+        f.interface(INTERFACE, ())
+        .add_s(
+            f.signal("DownloadProgress", ())
+            .arg(("filename", "s"))
+            .arg(("completed", "t"))
+            .arg(("total", "t"))
+        )
+        .add_s(
+            f.signal("ExtractProgress", ())
+            .arg(("filename", "s"))
+            .arg(("completed", "t"))
+            .arg(("total", "t"))
+        )
         .add_m(
             f.method("Bios", (), move |m| {
                 eprintln!("Bios");
@@ -260,9 +690,28 @@ pub fn bus() -> Result<(), String> {
             .outarg::<&str,_>("id")
         )
         .add_m(
+            f.method("Devices", (), move |m| {
+                eprintln!("Devices");
+                match devices() {
+                    Ok(devices) => {
+                        let mret = m.msg.method_return().append1(devices);
+                        Ok(vec![mret])
+                    },
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Err(MethodErr::failed(&err))
+                    }
+                }
+            })
+            .outarg::<Vec<(String, String, String, String)>,_>("devices")
+        )
+        .add_m({
+            let c = c.clone();
             f.method("Download", (), move |m| {
                 eprintln!("Download");
-                match download() {
+                match download(|filename, completed, total| {
+                    emit_progress(&c, "DownloadProgress", filename, completed, total);
+                }) {
                     Ok((digest, changelog)) => {
                         let mret = m.msg.method_return().append2(digest, changelog);
                         Ok(vec![mret])
@@ -275,12 +724,15 @@ pub fn bus() -> Result<(), String> {
             })
             .outarg::<&str,_>("digest")
             .outarg::<&str,_>("changelog")
-        )
-        .add_m(
+        })
+        .add_m({
+            let c = c.clone();
             f.method("Schedule", (), move |m| {
-                let digest = m.msg.read1()?;
-                eprintln!("Schedule({})", digest);
-                match schedule(digest) {
+                let (digest, allow_downgrade) = m.msg.read2()?;
+                eprintln!("Schedule({}, {})", digest, allow_downgrade);
+                match schedule(digest, allow_downgrade, |filename, completed, total| {
+                    emit_progress(&c, "ExtractProgress", filename, completed, total);
+                }) {
                     Ok(()) => {
                         let mret = m.msg.method_return();
                         Ok(vec![mret])
@@ -292,6 +744,42 @@ pub fn bus() -> Result<(), String> {
                 }
             })
             .inarg::<&str,_>("digest")
+            .inarg::<bool,_>("allow_downgrade")
+        })
+        .add_m(
+            f.method("InstallFromFile", (), move |m| {
+                let path: &str = m.msg.read1()?;
+                eprintln!("InstallFromFile({})", path);
+                match install_from_file(path) {
+                    Ok(()) => {
+                        let mret = m.msg.method_return();
+                        Ok(vec![mret])
+                    },
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Err(MethodErr::failed(&err))
+                    }
+                }
+            })
+            .inarg::<&str,_>("path")
+        )
+        .add_m(
+            f.method("CleanCache", (), move |m| {
+                let keep = m.msg.read1()?;
+                eprintln!("CleanCache({})", keep);
+                match clean_cache(keep) {
+                    Ok(removed) => {
+                        let mret = m.msg.method_return().append1(removed);
+                        Ok(vec![mret])
+                    },
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        Err(MethodErr::failed(&err))
+                    }
+                }
+            })
+            .inarg::<u32,_>("keep")
+            .outarg::<u32,_>("removed")
         )
         .add_m(
             f.method("Unschedule", (), move |m| {
@@ -328,3 +816,180 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod changelog_tests {
+    use super::*;
+
+    #[test]
+    fn reads_flat_schema() {
+        let changelog = serde_json::json!({"bios": "1.2.3"});
+        assert_eq!(changelog_version(&changelog, "bios"), Some("1.2.3".to_string()));
+    }
+
+    #[test]
+    fn reads_nested_versions_array() {
+        let changelog = serde_json::json!({"versions": [{"id": "ec", "version": "4.5.6"}]});
+        assert_eq!(changelog_version(&changelog, "ec"), Some("4.5.6".to_string()));
+    }
+
+    #[test]
+    fn flat_schema_takes_priority_over_nested() {
+        let changelog = serde_json::json!({
+            "bios": "1.2.3",
+            "versions": [{"id": "bios", "version": "9.9.9"}],
+        });
+        assert_eq!(changelog_version(&changelog, "bios"), Some("1.2.3".to_string()));
+    }
+
+    #[test]
+    fn missing_component_is_none() {
+        let changelog = serde_json::json!({"bios": "1.2.3"});
+        assert_eq!(changelog_version(&changelog, "me"), None);
+    }
+}
+
+#[cfg(test)]
+mod epoch_tests {
+    use super::*;
+
+    fn epoch_paths() -> (tempdir::TempDir, String, String) {
+        let dir = tempdir::TempDir::new("s76fw-epoch-test").unwrap();
+        let epoch_file = dir.path().join("epoch").to_string_lossy().into_owned();
+        let pending_file = dir.path().join("pending_epoch").to_string_lossy().into_owned();
+        (dir, epoch_file, pending_file)
+    }
+
+    #[test]
+    fn confirm_advances_stored_epoch_once_updater_dir_is_gone() {
+        let (dir, epoch_file, pending_file) = epoch_paths();
+        let updater_dir = dir.path().join("updater"); // never created: simulates a completed install
+
+        set_pending_epoch_at(&pending_file, 6);
+        confirm_pending_install_at(&pending_file, &epoch_file, &updater_dir).unwrap();
+
+        assert_eq!(read_epoch_file(&epoch_file).unwrap(), 6);
+        assert_eq!(read_epoch_file_opt(&pending_file).unwrap(), None);
+    }
+
+    #[test]
+    fn confirm_does_nothing_while_updater_dir_still_exists() {
+        let (dir, epoch_file, pending_file) = epoch_paths();
+        let updater_dir = dir.path().join("updater");
+        fs::create_dir_all(&updater_dir).unwrap();
+
+        set_pending_epoch_at(&pending_file, 6);
+        confirm_pending_install_at(&pending_file, &epoch_file, &updater_dir).unwrap();
+
+        assert_eq!(read_epoch_file(&epoch_file).unwrap(), 0);
+        assert_eq!(read_epoch_file_opt(&pending_file).unwrap(), Some(6));
+    }
+
+    #[test]
+    fn confirm_is_a_no_op_when_nothing_is_pending() {
+        let (dir, epoch_file, pending_file) = epoch_paths();
+        let updater_dir = dir.path().join("updater");
+
+        confirm_pending_install_at(&pending_file, &epoch_file, &updater_dir).unwrap();
+
+        assert_eq!(read_epoch_file(&epoch_file).unwrap(), 0);
+    }
+
+    // Regression test for the stale-pending bug: staging epoch 6, then re-staging (e.g. a
+    // re-flash, or an `allow_downgrade` call) down to epoch 5 before ever rebooting, must
+    // confirm 5 (what's actually installed) rather than the stale, since-superseded 6.
+    #[test]
+    fn restaging_a_lower_epoch_overwrites_a_stale_higher_pending_value() {
+        let (dir, epoch_file, pending_file) = epoch_paths();
+        let updater_dir = dir.path().join("updater");
+
+        write_epoch_file(&epoch_file, 5).unwrap();
+        set_pending_epoch_at(&pending_file, 6); // staged epoch 6, never rebooted into
+        set_pending_epoch_at(&pending_file, 5); // re-staged epoch 5 before rebooting
+
+        confirm_pending_install_at(&pending_file, &epoch_file, &updater_dir).unwrap();
+
+        assert_eq!(read_epoch_file(&epoch_file).unwrap(), 5);
+    }
+
+    #[test]
+    fn confirm_never_moves_the_stored_epoch_backwards() {
+        let (dir, epoch_file, pending_file) = epoch_paths();
+        let updater_dir = dir.path().join("updater");
+
+        write_epoch_file(&epoch_file, 7).unwrap();
+        set_pending_epoch_at(&pending_file, 3);
+
+        confirm_pending_install_at(&pending_file, &epoch_file, &updater_dir).unwrap();
+
+        assert_eq!(read_epoch_file(&epoch_file).unwrap(), 7);
+    }
+
+    fn set_pending_epoch_at(path: &str, epoch: u64) {
+        write_epoch_file(path, epoch).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod bundle_tests {
+    use super::*;
+
+    #[test]
+    fn list_files_recursive_finds_nested_files_with_relative_paths() {
+        let dir = tempdir::TempDir::new("s76fw-list-test").unwrap();
+        fs::write(dir.path().join("manifest.json"), b"{}").unwrap();
+        fs::create_dir_all(dir.path().join("firmware")).unwrap();
+        fs::write(dir.path().join("firmware").join("bios.rom"), b"x").unwrap();
+        fs::write(dir.path().join("firmware").join("bios.rom.sig"), b"y").unwrap();
+
+        let mut files: Vec<String> = list_files_recursive(dir.path()).unwrap()
+            .into_iter()
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .collect();
+        files.sort();
+
+        assert_eq!(files, vec![
+            "firmware/bios.rom".to_string(),
+            "firmware/bios.rom.sig".to_string(),
+            "manifest.json".to_string(),
+        ]);
+    }
+
+    fn bundle_files(entries: &[(&str, &str)]) -> serde_json::Map<String, serde_json::Value> {
+        entries.iter().map(|(name, sha256)| (name.to_string(), serde_json::Value::from(*sha256))).collect()
+    }
+
+    #[test]
+    fn verify_extracted_bundle_accepts_a_fully_listed_and_correct_bundle() {
+        let dir = tempdir::TempDir::new("s76fw-verify-test").unwrap();
+        fs::write(dir.path().join("manifest.json"), b"{}").unwrap();
+        fs::write(dir.path().join("payload"), b"firmware bytes").unwrap();
+
+        let files = bundle_files(&[("payload", &util::sha256(b"firmware bytes"))]);
+
+        assert!(verify_extracted_bundle(dir.path(), &files).is_ok());
+    }
+
+    #[test]
+    fn verify_extracted_bundle_rejects_a_member_not_listed_in_the_manifest() {
+        let dir = tempdir::TempDir::new("s76fw-verify-test").unwrap();
+        fs::write(dir.path().join("manifest.json"), b"{}").unwrap();
+        fs::write(dir.path().join("payload"), b"firmware bytes").unwrap();
+        fs::write(dir.path().join("system76-firmware-update"), b"tampered binary").unwrap();
+
+        let files = bundle_files(&[("payload", &util::sha256(b"firmware bytes"))]);
+
+        assert!(verify_extracted_bundle(dir.path(), &files).is_err());
+    }
+
+    #[test]
+    fn verify_extracted_bundle_rejects_a_checksum_mismatch() {
+        let dir = tempdir::TempDir::new("s76fw-verify-test").unwrap();
+        fs::write(dir.path().join("manifest.json"), b"{}").unwrap();
+        fs::write(dir.path().join("payload"), b"firmware bytes").unwrap();
+
+        let files = bundle_files(&[("payload", &util::sha256(b"different bytes"))]);
+
+        assert!(verify_extracted_bundle(dir.path(), &files).is_err());
+    }
+}
@@ -0,0 +1,15 @@
+use std::fs;
+
+// Management Engine version is exposed by the mei kernel driver, if loaded.
+pub fn me() -> Result<Option<String>, String> {
+    let path = "/sys/class/mei/mei0/fw_ver";
+
+    if !::std::path::Path::new(path).exists() {
+        return Ok(None);
+    }
+
+    let version = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read ME version: {}", err))?;
+
+    Ok(Some(version.trim().to_string()))
+}
@@ -0,0 +1,46 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+// RAII guard that mounts a filesystem on creation and unmounts it on drop, unless it was
+// already mounted beforehand (in which case it is left alone).
+pub struct Mount {
+    target: String,
+    owned: bool,
+}
+
+impl Mount {
+    pub fn new<P: AsRef<Path>>(target: P) -> io::Result<Mount> {
+        let target = target.as_ref();
+
+        if is_mounted(target)? {
+            return Ok(Mount {
+                target: target.display().to_string(),
+                owned: false,
+            });
+        }
+
+        let status = Command::new("mount").arg(target).status()?;
+        if !status.success() {
+            return Err(io::Error::new(io::ErrorKind::Other, format!("failed to mount {}", target.display())));
+        }
+
+        Ok(Mount {
+            target: target.display().to_string(),
+            owned: true,
+        })
+    }
+}
+
+impl Drop for Mount {
+    fn drop(&mut self) {
+        if self.owned {
+            let _ = Command::new("umount").arg(&self.target).status();
+        }
+    }
+}
+
+fn is_mounted(target: &Path) -> io::Result<bool> {
+    let mounts = ::std::fs::read_to_string("/proc/mounts")?;
+    Ok(mounts.lines().any(|line| line.split_whitespace().nth(1) == Some(&target.display().to_string())))
+}
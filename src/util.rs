@@ -0,0 +1,85 @@
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use tar::Archive;
+
+pub fn sha256(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+
+    let mut hex = String::with_capacity(64);
+    for b in hasher.result().iter() {
+        hex.push_str(&format!("{:02x}", b));
+    }
+    hex
+}
+
+fn xz_archive(data: &[u8]) -> io::Result<Archive<lzma::LzmaReader<&[u8]>>> {
+    let reader = lzma::LzmaReader::new_decompressor(data)?;
+    Ok(Archive::new(reader))
+}
+
+// Runs the LZMA decoder over `data` exactly once and returns the decompressed tar bytes, so
+// callers that need more than one pass over the tar entries (e.g. to count them before
+// unpacking) don't each pay for their own decompression.
+fn xz_decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = lzma::LzmaReader::new_decompressor(data)?;
+    let mut decompressed = Vec::new();
+    reader.read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+pub fn extract<P: AsRef<Path>>(data: &[u8], path: P) -> io::Result<()> {
+    extract_with_progress(data, path, |_completed, _total| {})
+}
+
+// Like `extract`, but calls `on_entry(completed, total)` after each archive member is
+// unpacked.
+pub fn extract_with_progress<P: AsRef<Path>, F: FnMut(u64, u64)>(data: &[u8], path: P, mut on_entry: F) -> io::Result<()> {
+    let path = path.as_ref();
+    fs::create_dir_all(path)?;
+
+    // Decompress once; the counting and unpacking passes below both read tar entries out of
+    // the in-memory buffer, rather than running the LZMA decoder over the whole archive twice.
+    let decompressed = xz_decompress(data)?;
+
+    let total = Archive::new(&decompressed[..]).entries()?.count() as u64;
+
+    let mut archive = Archive::new(&decompressed[..]);
+    for (i, entry) in archive.entries()?.enumerate() {
+        let mut entry = entry?;
+        entry.unpack_in(path)?;
+        on_entry(i as u64 + 1, total);
+    }
+
+    Ok(())
+}
+
+pub fn extract_file(data: &[u8], name: &str) -> io::Result<String> {
+    let mut archive = xz_archive(data)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.to_str() == Some(name) {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents)?;
+            return Ok(contents);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound, format!("{} not found in archive", name)))
+}
+
+// Verifies a detached Ed25519 signature over `data` against `public_key`. `signature` must
+// be the raw 64-byte Ed25519 signature (not a minisign-style base64 container with a header
+// line); callers are responsible for handing over the raw bytes of the `<name>.sig` sidecar.
+pub fn verify_signature(data: &[u8], signature: &[u8], public_key: &[u8; 32]) -> Result<(), String> {
+    let public_key = PublicKey::from_bytes(public_key)
+        .map_err(|err| format!("invalid public key: {}", err))?;
+    let signature = Signature::from_bytes(signature)
+        .map_err(|err| format!("invalid signature: {}", err))?;
+
+    public_key.verify(data, &signature)
+        .map_err(|_| format!("signature verification failed"))
+}